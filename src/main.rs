@@ -1,13 +1,196 @@
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::PathBuf;
 use std::process;
 use std::collections::HashSet;
+use std::str::FromStr;
+use std::fmt;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use jaq_interpret::{Ctx, FilterT, ParseCtx, RcIter, Val};
 use serde_json::{Value, Map};
 
+/// A jq-style `--filter` expression, compiled once up front so a bad
+/// expression is reported before any row is processed rather than per row.
+struct JqFilter {
+    filter: jaq_interpret::Filter,
+}
+
+impl JqFilter {
+    fn compile(src: &str) -> Result<Self, Box<dyn Error>> {
+        let mut ctx = ParseCtx::new(Vec::new());
+        ctx.insert_natives(jaq_core::core());
+        ctx.insert_defs(jaq_std::std());
+
+        let (main, errs) = jaq_parse::parse(src, jaq_parse::main());
+        if !errs.is_empty() {
+            let messages: Vec<String> = errs.iter().map(|e| e.to_string()).collect();
+            return Err(format!("invalid --filter expression: {}", messages.join("; ")).into());
+        }
+        let main = main.ok_or("invalid --filter expression: empty")?;
+
+        let filter = ctx.compile(main);
+        if !ctx.errs.is_empty() {
+            let messages: Vec<String> = ctx.errs.iter().map(|(e, _)| e.to_string()).collect();
+            return Err(format!("invalid --filter expression: {}", messages.join("; ")).into());
+        }
+
+        Ok(Self { filter })
+    }
+
+    /// Runs the filter against one record. jq filters can produce zero, one,
+    /// or many outputs per input (e.g. via `.[]`); `null` outputs are
+    /// dropped, matching the request to skip empty/null results.
+    fn apply(&self, input: Value) -> Result<Vec<Value>, Box<dyn Error>> {
+        let inputs = RcIter::new(core::iter::empty());
+        let ctx = Ctx::new(Vec::new(), &inputs);
+
+        let mut outputs = Vec::new();
+        for result in self.filter.run((ctx, Val::from(input))) {
+            let value = Value::from(result.map_err(|e| format!("--filter error: {e}"))?);
+            if !value.is_null() {
+                outputs.push(value);
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+/// The document shape `csv_to_json` writes and `json_to_csv` reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One JSON array containing every record (pretty-printed).
+    Array,
+    /// One compact JSON value per line (NDJSON / JSON Lines).
+    Ndjson,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Array => write!(f, "array"),
+            Format::Ndjson => write!(f, "ndjson"),
+        }
+    }
+}
+
+/// An explicit column type declared via the `name:type` header suffix convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Number,
+    Boolean,
+    String,
+}
+
+impl FromStr for ColumnType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(ColumnType::Number),
+            "boolean" => Ok(ColumnType::Boolean),
+            "string" => Ok(ColumnType::String),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::Number => write!(f, "number"),
+            ColumnType::Boolean => write!(f, "boolean"),
+            ColumnType::String => write!(f, "string"),
+        }
+    }
+}
+
+/// Splits a header like `price:number` into its column name and declared type,
+/// falling back to `None` when there's no `:` suffix or it isn't a known type.
+fn parse_header(header: &str) -> (String, Option<ColumnType>) {
+    if let Some(idx) = header.rfind(':') {
+        let (name, suffix) = header.split_at(idx);
+        let suffix = &suffix[1..];
+        if let Ok(col_type) = ColumnType::from_str(suffix) {
+            return (name.to_string(), Some(col_type));
+        }
+    }
+    (header.to_string(), None)
+}
+
+fn has_leading_zero(raw: &str) -> bool {
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit()
+}
+
+/// Parses `raw` as an `i64`, rejecting values with a leading zero (e.g. `"007"`)
+/// so they round-trip as strings instead of being silently reinterpreted.
+fn parse_int(raw: &str) -> Option<i64> {
+    if has_leading_zero(raw) {
+        return None;
+    }
+    raw.parse::<i64>().ok()
+}
+
+/// Parses `raw` as an `f64`, rejecting values with a leading zero (e.g.
+/// `"007"`) for the same reason `parse_int` does: `f64::from_str` accepts
+/// them happily, which would otherwise let zip-code-like values round-trip
+/// through the integer guard only to get reinterpreted as floats.
+fn parse_float(raw: &str) -> Option<serde_json::Number> {
+    if has_leading_zero(raw) {
+        return None;
+    }
+    raw.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+}
+
+/// Tries to parse a raw CSV cell as an integer, float, boolean, or null, falling
+/// back to a plain string when none of those match.
+fn infer_value(raw: &str) -> Value {
+    if raw.is_empty() {
+        return Value::Null;
+    }
+    if let Some(i) = parse_int(raw) {
+        return Value::Number(i.into());
+    }
+    if let Some(num) = parse_float(raw) {
+        return Value::Number(num);
+    }
+    match raw {
+        "true" => return Value::Bool(true),
+        "false" => return Value::Bool(false),
+        _ => {}
+    }
+    Value::String(raw.to_string())
+}
+
+/// Coerces a raw CSV cell into the explicitly declared column type, erroring
+/// out if the cell can't be parsed as that type.
+fn coerce_value(raw: &str, col_type: ColumnType) -> Result<Value, Box<dyn Error>> {
+    if raw.is_empty() {
+        return Ok(Value::Null);
+    }
+    match col_type {
+        ColumnType::Number => {
+            if let Ok(i) = raw.parse::<i64>() {
+                return Ok(Value::Number(i.into()));
+            }
+            let f: f64 = raw
+                .parse()
+                .map_err(|_| format!("cannot parse {raw:?} as a number"))?;
+            let num = serde_json::Number::from_f64(f)
+                .ok_or_else(|| format!("cannot parse {raw:?} as a number"))?;
+            Ok(Value::Number(num))
+        }
+        ColumnType::Boolean => match raw {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("cannot parse {raw:?} as a boolean").into()),
+        },
+        ColumnType::String => Ok(Value::String(raw.to_string())),
+    }
+}
+
 #[derive(Parser)]
 #[clap(name = "csv-json-converter")]
 #[clap(about = "A CLI tool to convert between CSV and JSON formats")]
@@ -19,49 +202,126 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Convert CSV to JSON
-    CsvToJson {
-        /// Input CSV file
-        #[clap(short, long)]
-        input: PathBuf,
-        
-        /// Output JSON file
-        #[clap(short, long)]
-        output: PathBuf,
-        
-        /// Output as array of objects (default) or as array of arrays
-        #[clap(short, long, default_value = "false")]
-        array_format: bool,
-        
-        /// CSV delimiter character (default: ,)
-        #[clap(short, long, default_value = ",")]
-        delimiter: char,
-        
-        /// CSV has headers (default: true)
-        #[clap(long, default_value = "true")]
-        has_headers: bool,
-        
-        /// Trim whitespace from fields (default: false)
-        #[clap(long, default_value = "false")]
-        trim: bool,
-    },
+    CsvToJson(CsvToJsonArgs),
     /// Convert JSON to CSV
-    JsonToCsv {
-        /// Input JSON file
-        #[clap(short, long)]
-        input: PathBuf,
-        
-        /// Output CSV file
-        #[clap(short, long)]
-        output: PathBuf,
-        
-        /// CSV delimiter character (default: ,)
-        #[clap(short, long, default_value = ",")]
-        delimiter: char,
-        
-        /// Quote all non-numeric fields (default: false)
-        #[clap(long, default_value = "false")]
-        quote_all: bool,
-    },
+    JsonToCsv(JsonToCsvArgs),
+}
+
+#[derive(Args)]
+struct CsvToJsonArgs {
+    /// Input CSV file
+    #[clap(short, long)]
+    input: PathBuf,
+
+    /// Output JSON file
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// Output as array of objects (default) or as array of arrays
+    #[clap(short, long, default_value = "false")]
+    array_format: bool,
+
+    /// CSV delimiter character (default: ,)
+    #[clap(short, long, default_value = ",")]
+    delimiter: char,
+
+    /// CSV has headers (default: true)
+    #[clap(long, default_value = "true")]
+    has_headers: bool,
+
+    /// Trim whitespace from fields (default: false)
+    #[clap(long, default_value = "false")]
+    trim: bool,
+
+    /// Infer numbers, booleans, and nulls from field values instead of
+    /// emitting every field as a string (default: false). Applies to object
+    /// output read with a header row; with `--array-format` or without
+    /// headers every field is still emitted as a string
+    #[clap(long, default_value = "false")]
+    infer_types: bool,
+
+    /// Output document shape: a single pretty-printed array, or one
+    /// compact JSON value per line (NDJSON)
+    #[clap(long, value_enum, default_value_t = Format::Array)]
+    format: Format,
+
+    /// Only emit these columns, in this order (comma-separated). Matched
+    /// against the header row; ignored when the CSV has no headers
+    #[clap(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// Stop after emitting this many data records
+    #[clap(long)]
+    num_rows: Option<usize>,
+
+    /// Interpret dotted keys (`addr.city`) and bracketed keys (`tags[0]`) as
+    /// nested object/array paths and rebuild the nested JSON structure
+    #[clap(long, default_value = "false")]
+    unflatten: bool,
+
+    /// Separator used to join nested keys when unflattening
+    #[clap(long, default_value = ".")]
+    separator: String,
+
+    /// A jq-style expression applied to each emitted object before it's
+    /// written (e.g. `{name, total: (.qty|tonumber)}`); outputs that are
+    /// `null` or that the filter drops entirely are skipped
+    #[clap(long)]
+    filter: Option<String>,
+}
+
+#[derive(Args)]
+struct JsonToCsvArgs {
+    /// Input JSON file
+    #[clap(short, long)]
+    input: PathBuf,
+
+    /// Output CSV file
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// CSV delimiter character (default: ,)
+    #[clap(short, long, default_value = ",")]
+    delimiter: char,
+
+    /// Quote all non-numeric fields (default: false)
+    #[clap(long, default_value = "false")]
+    quote_all: bool,
+
+    /// Read the input as NDJSON (one JSON value per line) instead of a
+    /// single JSON array
+    #[clap(long, default_value = "false")]
+    ndjson: bool,
+
+    /// Only emit these columns, in this order (comma-separated)
+    #[clap(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// Stop after writing this many data records
+    #[clap(long)]
+    num_rows: Option<usize>,
+
+    /// Recursively flatten nested objects/arrays into dotted/bracketed
+    /// column keys (e.g. `addr.city`, `tags[0]`) instead of stringifying them
+    #[clap(long, default_value = "false")]
+    flatten: bool,
+
+    /// Separator used to join nested keys when flattening
+    #[clap(long, default_value = ".")]
+    separator: String,
+
+    /// Build the header row from only the first record instead of scanning
+    /// every record first. With `--ndjson` this leaves a single full
+    /// streaming pass after the read up to the first record; any record with a key the first one
+    /// doesn't have is silently missing that column.
+    #[clap(long, default_value = "false")]
+    headers_from_first: bool,
+
+    /// A jq-style expression applied to each array element before header
+    /// collection (e.g. `select(.active)`); outputs that are `null` or that
+    /// the filter drops entirely are skipped
+    #[clap(long)]
+    filter: Option<String>,
 }
 
 fn main() {
@@ -75,231 +335,860 @@ fn main() {
 
 fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
     match cli.command {
-        Commands::CsvToJson { 
-            input, 
-            output, 
-            array_format, 
-            delimiter, 
-            has_headers, 
-            trim 
-        } => {
-            csv_to_json(input, output, array_format, delimiter, has_headers, trim)?;
-        }
-        Commands::JsonToCsv { 
-            input, 
-            output, 
-            delimiter, 
-            quote_all 
-        } => {
-            json_to_csv(input, output, delimiter, quote_all)?;
-        }
+        Commands::CsvToJson(args) => csv_to_json(args)?,
+        Commands::JsonToCsv(args) => json_to_csv(args)?,
     }
 
     Ok(())
 }
 
-fn csv_to_json(
-    input: PathBuf, 
-    output: PathBuf, 
-    array_format: bool,
-    delimiter: char,
-    has_headers: bool,
-    trim: bool
-) -> Result<(), Box<dyn Error>> {
+fn csv_to_json(args: CsvToJsonArgs) -> Result<(), Box<dyn Error>> {
+    let CsvToJsonArgs {
+        input,
+        output,
+        array_format,
+        delimiter,
+        has_headers,
+        trim,
+        infer_types,
+        format,
+        columns,
+        num_rows,
+        unflatten,
+        separator,
+        filter,
+    } = args;
+
+    if array_format && filter.is_some() {
+        return Err("--filter is not supported together with --array-format: jq expressions expect object input, not CSV rows".into());
+    }
+
+    // Compile the filter up front so a bad expression is reported before any
+    // row is processed rather than per row
+    let jq_filter = filter.as_deref().map(JqFilter::compile).transpose()?;
+
     // Open the CSV file
     let file = File::open(input)?;
     let reader = BufReader::new(file);
-    
+
     // Create a new CSV reader with the specified options
     let builder = csv::ReaderBuilder::new()
         .delimiter(delimiter as u8)
         .has_headers(has_headers)
         .trim(if trim { csv::Trim::All } else { csv::Trim::None })
         .from_reader(reader);
-    
+
     let mut csv_reader = builder;
-    
-    // Prepare the output file
+
+    // Prepare the output file and a sink that writes NDJSON records as
+    // they're produced instead of buffering them for a pretty-printed array
     let file = File::create(output)?;
     let writer = BufWriter::new(file);
-    
+    let mut sink = JsonSink::new(writer, format);
+
     if array_format {
-        // Create an array of arrays format
-        let mut rows = Vec::new();
-        
-        // If there are headers, add them as the first row
-        if has_headers {
+        // Array of arrays format. If there are headers, emit them as the
+        // first row, restricted/reordered to the requested columns
+        let indices = if has_headers {
             let headers: Vec<String> = csv_reader.headers()?
                 .iter()
                 .map(String::from)
                 .collect();
-            rows.push(headers);
-        }
-        
-        // Add data rows
-        for result in csv_reader.records() {
+            let indices = column_indices(&headers, &columns)?;
+            sink.push(Value::Array(
+                indices.iter().map(|&i| Value::String(headers[i].clone())).collect(),
+            ))?;
+            indices
+        } else {
+            Vec::new()
+        };
+
+        // Add data rows. `--filter` is rejected above when `--array-format`
+        // is set, so input rows and emitted rows are always 1:1 here and
+        // `.take()` is exact.
+        for result in csv_reader.records().take(num_rows.unwrap_or(usize::MAX)) {
             let record = result?;
-            let row: Vec<String> = record.iter().map(String::from).collect();
-            rows.push(row);
+            let row: Vec<Value> = if has_headers {
+                indices.iter().map(|&i| Value::String(record.get(i).unwrap_or("").to_string())).collect()
+            } else {
+                record.iter().map(|v| Value::String(v.to_string())).collect()
+            };
+            sink.push(Value::Array(row))?;
         }
-        
-        serde_json::to_writer_pretty(writer, &rows)?;
     } else {
-        // Create an array of objects format
-        let mut json_array = Vec::new();
-        
+        // Array of objects format. `--num-rows` caps emitted records, not
+        // raw CSV rows read, so with a filter in play we stop reading as
+        // soon as enough filtered records have been produced.
+        let mut emitted = 0;
+
         if has_headers {
-            // Get headers once
-            let headers = csv_reader.headers()?.clone();
-            
-            // Process records
-            for result in csv_reader.records() {
+            // Get headers once, splitting off any `name:type` suffix, and
+            // resolve them against the requested columns
+            let parsed_headers: Vec<(String, Option<ColumnType>)> = csv_reader
+                .headers()?
+                .iter()
+                .map(parse_header)
+                .collect();
+            let names: Vec<String> = parsed_headers.iter().map(|(name, _)| name.clone()).collect();
+            let indices = column_indices(&names, &columns)?;
+
+            'records: for result in csv_reader.records() {
                 let record = result?;
                 let mut obj = Map::new();
-                
-                for (i, header) in headers.iter().enumerate() {
-                    if let Some(value) = record.get(i) {
-                        obj.insert(header.to_string(), Value::String(value.to_string()));
+
+                for &i in &indices {
+                    let (name, col_type) = &parsed_headers[i];
+                    if let Some(raw) = record.get(i) {
+                        let value = match col_type {
+                            Some(col_type) => coerce_value(raw, *col_type)?,
+                            None if infer_types => infer_value(raw),
+                            None => Value::String(raw.to_string()),
+                        };
+                        obj.insert(name.clone(), value);
+                    }
+                }
+
+                let record_value = if unflatten { unflatten_object(&obj, &separator) } else { Value::Object(obj) };
+                let outputs = match &jq_filter {
+                    Some(filter) => filter.apply(record_value)?,
+                    None => vec![record_value],
+                };
+                for value in outputs {
+                    if num_rows.is_some_and(|n| emitted >= n) {
+                        break 'records;
                     }
+                    sink.push(value)?;
+                    emitted += 1;
                 }
-                
-                json_array.push(Value::Object(obj));
             }
         } else {
-            // No headers, use positional indices
-            let mut _record_num = 0;
-            for result in csv_reader.records() {
+            // No headers, use positional indices. Same "cap emitted
+            // records, not rows read" rule as above.
+            'records: for result in csv_reader.records() {
                 let record = result?;
                 let mut obj = Map::new();
-                
+
                 for (i, value) in record.iter().enumerate() {
                     obj.insert(format!("field{}", i), Value::String(value.to_string()));
                 }
-                
-                json_array.push(Value::Object(obj));
-                _record_num += 1;
+
+                let outputs = match &jq_filter {
+                    Some(filter) => filter.apply(Value::Object(obj))?,
+                    None => vec![Value::Object(obj)],
+                };
+                for value in outputs {
+                    if num_rows.is_some_and(|n| emitted >= n) {
+                        break 'records;
+                    }
+                    sink.push(value)?;
+                    emitted += 1;
+                }
             }
         }
-        
-        serde_json::to_writer_pretty(writer, &json_array)?;
     }
-    
+
+    sink.finish()?;
     println!("CSV successfully converted to JSON");
     Ok(())
 }
 
-fn json_to_csv(
-    input: PathBuf, 
-    output: PathBuf,
-    delimiter: char,
-    quote_all: bool
-) -> Result<(), Box<dyn Error>> {
-    // Open the JSON file
-    let file = File::open(input)?;
-    let reader = BufReader::new(file);
-    let json: Value = serde_json::from_reader(reader)?;
-    
+/// Resolves `--columns` against the available header names, returning the
+/// indices to keep in the requested order. Errors out if a requested column
+/// doesn't exist.
+fn column_indices(headers: &[String], columns: &Option<Vec<String>>) -> Result<Vec<usize>, Box<dyn Error>> {
+    match columns {
+        None => Ok((0..headers.len()).collect()),
+        Some(columns) => columns
+            .iter()
+            .map(|col| {
+                headers
+                    .iter()
+                    .position(|h| h == col)
+                    .ok_or_else(|| format!("column {col:?} not found in headers").into())
+            })
+            .collect(),
+    }
+}
+
+/// Restricts/reorders a set of header names to the requested `--columns`
+/// list. Errors out if a requested column isn't among the available headers.
+fn select_headers(available: Vec<String>, columns: &Option<Vec<String>>) -> Result<Vec<String>, Box<dyn Error>> {
+    match columns {
+        None => Ok(available),
+        Some(columns) => columns
+            .iter()
+            .map(|col| {
+                if available.contains(col) {
+                    Ok(col.clone())
+                } else {
+                    Err(format!("column {col:?} not found in headers").into())
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Writes records out as they're produced instead of collecting them into a
+/// `Vec<Value>` first. NDJSON records are written (and newline-terminated)
+/// immediately, keeping memory bounded regardless of input size; array
+/// format still has to buffer every record, since a single pretty-printed
+/// JSON array can't be written until all of its elements are known.
+enum JsonSink<W: Write> {
+    Array { writer: W, values: Vec<Value> },
+    Ndjson { writer: W },
+}
+
+impl<W: Write> JsonSink<W> {
+    fn new(writer: W, format: Format) -> Self {
+        match format {
+            Format::Array => JsonSink::Array { writer, values: Vec::new() },
+            Format::Ndjson => JsonSink::Ndjson { writer },
+        }
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), Box<dyn Error>> {
+        match self {
+            JsonSink::Array { values, .. } => values.push(value),
+            JsonSink::Ndjson { writer } => {
+                serde_json::to_writer(&mut *writer, &value)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        if let JsonSink::Array { writer, values } = self {
+            serde_json::to_writer_pretty(writer, &values)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recursively walks `value`, writing one entry per leaf into `out` keyed by
+/// its dotted/bracketed path (`addr.city`, `tags[0]`).
+fn flatten_into(value: &Value, prefix: &str, separator: &str, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}{separator}{key}")
+                };
+                flatten_into(child, &path, separator, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, child) in items.iter().enumerate() {
+                let path = format!("{prefix}[{i}]");
+                flatten_into(child, &path, separator, out);
+            }
+        }
+        leaf => {
+            out.insert(prefix.to_string(), leaf.clone());
+        }
+    }
+}
+
+/// Flattens a top-level JSON object into dotted/bracketed columns.
+fn flatten_object(map: &Map<String, Value>, separator: &str) -> Map<String, Value> {
+    let mut out = Map::new();
+    for (key, value) in map {
+        flatten_into(value, key, separator, &mut out);
+    }
+    out
+}
+
+/// One segment of a parsed flattened-key path: a named object field, or an
+/// array index introduced by a `[n]` suffix.
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Splits a flattened key like `addr.city` or `tags[0]` into path segments,
+/// using `separator` between object levels and `[n]` for array indices.
+fn tokenize_path(key: &str, separator: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    for part in key.split(separator) {
+        let mut rest = part;
+        if let Some(bracket) = rest.find('[') {
+            let name = &rest[..bracket];
+            if !name.is_empty() {
+                segments.push(PathSegment::Key(name.to_string()));
+            }
+            rest = &rest[bracket..];
+            while let Some(stripped) = rest.strip_prefix('[') {
+                let Some(end) = stripped.find(']') else { break };
+                if let Ok(index) = stripped[..end].parse::<usize>() {
+                    segments.push(PathSegment::Index(index));
+                }
+                rest = &stripped[end + 1..];
+            }
+        } else {
+            segments.push(PathSegment::Key(rest.to_string()));
+        }
+    }
+    segments
+}
+
+/// Writes `value` into `root` at the nested path described by `segments`,
+/// creating intermediate objects/arrays and filling sparse array slots with
+/// `Value::Null` as needed.
+fn set_path(root: &mut Value, segments: &[PathSegment], value: Value) {
+    match segments.first() {
+        None => *root = value,
+        Some(PathSegment::Key(key)) => {
+            if !root.is_object() {
+                *root = Value::Object(Map::new());
+            }
+            let entry = root.as_object_mut().unwrap().entry(key.clone()).or_insert(Value::Null);
+            set_path(entry, &segments[1..], value);
+        }
+        Some(PathSegment::Index(index)) => {
+            if !root.is_array() {
+                *root = Value::Array(Vec::new());
+            }
+            let array = root.as_array_mut().unwrap();
+            if array.len() <= *index {
+                array.resize(*index + 1, Value::Null);
+            }
+            set_path(&mut array[*index], &segments[1..], value);
+        }
+    }
+}
+
+/// Rebuilds a nested JSON object from a flat map of dotted/bracketed keys.
+fn unflatten_object(flat: &Map<String, Value>, separator: &str) -> Value {
+    let mut root = Value::Object(Map::new());
+    for (key, value) in flat {
+        let segments = tokenize_path(key, separator);
+        set_path(&mut root, &segments, value.clone());
+    }
+    root
+}
+
+/// Renders a leaf `Value` as a CSV cell: strings pass through unquoted by
+/// serde, nulls become empty cells, everything else uses its JSON rendering.
+fn stringify(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// Flattens a record's top-level object into dotted/bracketed columns when
+/// `flatten` is set; arrays and anything else pass through unchanged.
+fn flatten_record(record: Value, flatten: bool, separator: &str) -> Value {
+    if !flatten {
+        return record;
+    }
+    match record {
+        Value::Object(map) => Value::Object(flatten_object(&map, separator)),
+        other => other,
+    }
+}
+
+/// Runs `record` through `filter` if one is given, returning its outputs
+/// (`null` outputs already dropped); with no filter, passes `record` through
+/// unchanged as the sole output.
+fn filtered_records(record: Value, filter: Option<&JqFilter>) -> Result<Vec<Value>, Box<dyn Error>> {
+    match filter {
+        Some(filter) => filter.apply(record),
+        None => Ok(vec![record]),
+    }
+}
+
+fn json_to_csv(args: JsonToCsvArgs) -> Result<(), Box<dyn Error>> {
+    let JsonToCsvArgs {
+        input,
+        output,
+        delimiter,
+        quote_all,
+        ndjson,
+        columns,
+        num_rows,
+        flatten,
+        separator,
+        headers_from_first,
+        filter,
+    } = args;
+
+    // Compile the filter up front so a bad expression is reported before any
+    // row is processed rather than per row
+    let jq_filter = filter.as_deref().map(JqFilter::compile).transpose()?;
+
     // Prepare the output file
     let file = File::create(output)?;
-    
+
     // Configure the CSV writer
-    let writer = csv::WriterBuilder::new()
+    let mut csv_writer = csv::WriterBuilder::new()
         .delimiter(delimiter as u8)
-        .quote_style(if quote_all { 
-            csv::QuoteStyle::Always 
-        } else { 
-            csv::QuoteStyle::Necessary 
+        .quote_style(if quote_all {
+            csv::QuoteStyle::Always
+        } else {
+            csv::QuoteStyle::Necessary
         })
         .from_writer(file);
-    
-    let mut csv_writer = writer;
-    
-    // Process based on JSON format
-    match json {
-        Value::Array(array) => {
-            if array.is_empty() {
-                return Ok(());
+
+    let options = CsvWriteOptions {
+        columns,
+        num_rows,
+        flatten,
+        separator,
+        headers_from_first,
+        jq_filter: jq_filter.as_ref(),
+    };
+    if ndjson {
+        json_to_csv_streaming(&input, &mut csv_writer, options)?;
+    } else {
+        json_to_csv_eager(&input, &mut csv_writer, options)?;
+    }
+
+    // Flush the writer to ensure all data is written
+    csv_writer.flush()?;
+    println!("JSON successfully converted to CSV");
+    Ok(())
+}
+
+/// Bundles the row-selection/shaping options shared by [`json_to_csv_eager`]
+/// and [`json_to_csv_streaming`], which would otherwise need more positional
+/// arguments than clippy allows.
+struct CsvWriteOptions<'a> {
+    columns: Option<Vec<String>>,
+    num_rows: Option<usize>,
+    flatten: bool,
+    separator: String,
+    headers_from_first: bool,
+    jq_filter: Option<&'a JqFilter>,
+}
+
+/// Parses the whole input document into one `Value`, holding every record in
+/// memory at once. Simple and fine for small-to-medium files; for anything
+/// too big to fit in RAM, convert it to NDJSON first and pass `--ndjson` to
+/// take the streaming path instead.
+fn json_to_csv_eager<W: Write>(
+    input: &PathBuf,
+    csv_writer: &mut csv::Writer<W>,
+    options: CsvWriteOptions<'_>,
+) -> Result<(), Box<dyn Error>> {
+    let CsvWriteOptions {
+        columns,
+        num_rows,
+        flatten,
+        separator,
+        headers_from_first,
+        jq_filter,
+    } = options;
+    let separator = separator.as_str();
+
+    let file = File::open(input)?;
+    let reader = BufReader::new(file);
+    let json: Value = serde_json::from_reader(reader)?;
+
+    let Value::Array(array) = json else {
+        return Err("JSON must be an array".into());
+    };
+    if array.is_empty() {
+        return Ok(());
+    }
+
+    let array: Vec<Value> = array
+        .into_iter()
+        .map(|record| filtered_records(record, jq_filter))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .map(|record| flatten_record(record, flatten, separator))
+        .collect();
+    if array.is_empty() {
+        return Ok(());
+    }
+
+    match &array[0] {
+        Value::Array(_) => {
+            // Array of arrays format
+            for (i, row) in array.iter().take(num_rows.unwrap_or(usize::MAX)).enumerate() {
+                let Value::Array(values) = row else {
+                    return Err(format!("Row {i} is not an array").into());
+                };
+                let str_values: Vec<String> = values.iter().map(stringify).collect();
+                csv_writer.write_record(&str_values)?;
             }
-            
-            // Check if array of arrays or array of objects
-            match &array[0] {
-                Value::Array(_) => {
-                    // Array of arrays format
-                    for (i, row) in array.iter().enumerate() {
-                        if let Value::Array(values) = row {
-                            let str_values: Vec<String> = values
-                                .iter()
-                                .map(|v| match v {
-                                    Value::String(s) => s.clone(),
-                                    Value::Null => String::new(),
-                                    _ => v.to_string(),
-                                })
-                                .collect();
-                            
-                            csv_writer.write_record(&str_values)?;
-                        } else {
-                            return Err(format!("Row {i} is not an array").into());
+        }
+        Value::Object(_) => {
+            // Array of objects format. Preserve the order of headers from
+            // the first object and, unless `--headers-from-first` is set,
+            // add any additional headers found in later objects.
+            let mut ordered_headers: Vec<String> = Vec::new();
+            let mut seen_headers = HashSet::new();
+
+            if let Value::Object(first_obj) = &array[0] {
+                for key in first_obj.keys() {
+                    ordered_headers.push(key.clone());
+                    seen_headers.insert(key.clone());
+                }
+            }
+
+            if !headers_from_first {
+                for obj in &array {
+                    if let Value::Object(map) = obj {
+                        for key in map.keys() {
+                            if seen_headers.insert(key.clone()) {
+                                ordered_headers.push(key.clone());
+                            }
                         }
                     }
-                },
-                Value::Object(_) => {
-                    // Array of objects format
-                    // Preserve the order of headers from the first object
-                    // and add any additional headers from other objects
-                    let mut ordered_headers: Vec<String> = Vec::new();
-                    let mut seen_headers = HashSet::new();
-                    
-                    // First, collect headers from the first object to establish initial order
-                    if let Value::Object(first_obj) = &array[0] {
-                        for key in first_obj.keys() {
-                            ordered_headers.push(key.clone());
-                            seen_headers.insert(key.clone());
-                        }
+                }
+            }
+
+            // Restrict/reorder to the requested columns, if any
+            let ordered_headers = select_headers(ordered_headers, &columns)?;
+
+            // Write headers
+            csv_writer.write_record(&ordered_headers)?;
+
+            // Write data rows
+            for obj in array.iter().take(num_rows.unwrap_or(usize::MAX)) {
+                if let Value::Object(map) = obj {
+                    let row: Vec<String> = ordered_headers
+                        .iter()
+                        .map(|header| map.get(header).map(stringify).unwrap_or_default())
+                        .collect();
+
+                    csv_writer.write_record(&row)?;
+                }
+            }
+        }
+        _ => return Err("JSON array must contain arrays or objects".into()),
+    }
+
+    Ok(())
+}
+
+/// Calls `f` once per non-blank NDJSON line, reopening `input` for each pass
+/// so callers can scan it more than once without holding every line in
+/// memory at the same time. `f` returns whether scanning should continue,
+/// so a caller that's satisfied (e.g. it found what it was looking for, or
+/// it's hit a row cap) can stop reading without scanning the rest of the
+/// file.
+fn for_each_ndjson_record(
+    input: &PathBuf,
+    mut f: impl FnMut(Value) -> Result<bool, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let file = File::open(input)?;
+    let reader = BufReader::new(file);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !f(serde_json::from_str(&line)?)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Converts NDJSON input (one JSON value per line) to CSV without ever
+/// holding the whole document in memory. A first read stops at the first
+/// record the filter keeps, to tell arrays from objects. After that, for
+/// array-of-objects input this takes two full passes over the file unless
+/// `headers_from_first` is set: one to collect the full header union, one to
+/// write rows; `headers_from_first` collapses that into a single full pass at
+/// the cost of dropping columns that only appear on later records.
+fn json_to_csv_streaming<W: Write>(
+    input: &PathBuf,
+    csv_writer: &mut csv::Writer<W>,
+    options: CsvWriteOptions<'_>,
+) -> Result<(), Box<dyn Error>> {
+    let CsvWriteOptions {
+        columns,
+        num_rows,
+        flatten,
+        separator,
+        headers_from_first,
+        jq_filter,
+    } = options;
+    let separator = separator.as_str();
+
+    // Find the first record the filter doesn't drop, to detect the
+    // array-of-arrays vs array-of-objects shape. Stop as soon as it's found
+    // instead of scanning the rest of the file.
+    let mut first = None;
+    for_each_ndjson_record(input, |record| {
+        first = filtered_records(record, jq_filter)?.into_iter().next();
+        Ok(first.is_none())
+    })?;
+    let Some(first) = first else {
+        return Ok(());
+    };
+    let first = flatten_record(first, flatten, separator);
+
+    match &first {
+        Value::Array(_) => {
+            let mut written = 0;
+            for_each_ndjson_record(input, |record| {
+                for record in filtered_records(record, jq_filter)? {
+                    if num_rows.is_some_and(|n| written >= n) {
+                        return Ok(false);
                     }
-                    
-                    // Then collect any additional headers from other objects
-                    for obj in &array {
-                        if let Value::Object(map) = obj {
+                    let Value::Array(values) = record else {
+                        return Err("NDJSON rows must all be arrays or all be objects".into());
+                    };
+                    csv_writer.write_record(values.iter().map(stringify).collect::<Vec<_>>())?;
+                    written += 1;
+                }
+                Ok(true)
+            })?;
+        }
+        Value::Object(first_map) => {
+            let mut ordered_headers: Vec<String> = first_map.keys().cloned().collect();
+
+            if !headers_from_first {
+                let mut seen_headers: HashSet<String> = ordered_headers.iter().cloned().collect();
+                for_each_ndjson_record(input, |record| {
+                    for record in filtered_records(record, jq_filter)? {
+                        if let Value::Object(map) = flatten_record(record, flatten, separator) {
                             for key in map.keys() {
-                                if !seen_headers.contains(key) {
+                                if seen_headers.insert(key.clone()) {
                                     ordered_headers.push(key.clone());
-                                    seen_headers.insert(key.clone());
                                 }
                             }
                         }
                     }
-                    
-                    // Write headers
-                    csv_writer.write_record(&ordered_headers)?;
-                    
-                    // Write data rows
-                    for obj in &array {
-                        if let Value::Object(map) = obj {
-                            let row: Vec<String> = ordered_headers
-                                .iter()
-                                .map(|header| {
-                                    map.get(header)
-                                        .map(|v| match v {
-                                            Value::String(s) => s.clone(),
-                                            Value::Null => String::new(),
-                                            _ => v.to_string(),
-                                        })
-                                        .unwrap_or_default()
-                                })
-                                .collect();
-                            
-                            csv_writer.write_record(&row)?;
-                        }
-                    }
-                },
-                _ => return Err("JSON array must contain arrays or objects".into()),
+                    Ok(true)
+                })?;
             }
-        },
-        _ => return Err("JSON must be an array".into()),
+
+            let ordered_headers = select_headers(ordered_headers, &columns)?;
+            csv_writer.write_record(&ordered_headers)?;
+
+            let mut written = 0;
+            for_each_ndjson_record(input, |record| {
+                for record in filtered_records(record, jq_filter)? {
+                    if num_rows.is_some_and(|n| written >= n) {
+                        return Ok(false);
+                    }
+                    if let Value::Object(map) = flatten_record(record, flatten, separator) {
+                        let row: Vec<String> = ordered_headers
+                            .iter()
+                            .map(|header| map.get(header).map(stringify).unwrap_or_default())
+                            .collect();
+                        csv_writer.write_record(&row)?;
+                        written += 1;
+                    }
+                }
+                Ok(true)
+            })?;
+        }
+        _ => return Err("NDJSON records must be arrays or objects".into()),
     }
-    
-    // Flush the writer to ensure all data is written
-    csv_writer.flush()?;
-    println!("JSON successfully converted to CSV");
+
     Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unique path under the system temp dir so parallel test runs don't
+    /// collide on the same file.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cjconv_test_{}_{name}", process::id()))
+    }
+
+    fn write_file(path: &PathBuf, contents: &str) {
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn read_file(path: &PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn infer_value_keeps_leading_zero_values_as_strings() {
+        assert_eq!(infer_value("007"), Value::String("007".to_string()));
+        assert_eq!(infer_value("007.5"), Value::String("007.5".to_string()));
+        assert_eq!(infer_value("042"), Value::String("042".to_string()));
+        assert_eq!(infer_value("1.5"), serde_json::json!(1.5));
+        assert_eq!(infer_value("0"), serde_json::json!(0));
+    }
+
+    #[test]
+    fn jq_filter_drops_nulls_and_fans_out_multiple_outputs() {
+        let filter = JqFilter::compile(".[]").unwrap();
+        let outputs = filter.apply(serde_json::json!([1, null, 2])).unwrap();
+        assert_eq!(outputs, vec![serde_json::json!(1), serde_json::json!(2)]);
+    }
+
+    #[test]
+    fn csv_to_json_num_rows_caps_emitted_records_not_rows_read() {
+        // Rows 1, 3, and 4 match the filter; --num-rows 2 should stop once
+        // two *matching* records have been emitted, not after reading two
+        // raw CSV rows (which would only find row 1 a match).
+        let input = temp_path("csv_to_json_rows.csv");
+        write_file(&input, "a\n1\n2\n1\n1\n");
+        let output = temp_path("csv_to_json_rows.json");
+
+        let args = CsvToJsonArgs {
+            input: input.clone(),
+            output: output.clone(),
+            array_format: false,
+            delimiter: ',',
+            has_headers: true,
+            trim: false,
+            infer_types: false,
+            format: Format::Array,
+            columns: None,
+            num_rows: Some(2),
+            unflatten: false,
+            separator: ".".to_string(),
+            filter: Some("select(.a == \"1\")".to_string()),
+        };
+        csv_to_json(args).unwrap();
+
+        let parsed: Value = serde_json::from_str(&read_file(&output)).unwrap();
+        assert_eq!(parsed, serde_json::json!([{"a": "1"}, {"a": "1"}]));
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn csv_to_json_num_rows_zero_emits_nothing() {
+        let input = temp_path("csv_to_json_zero.csv");
+        write_file(&input, "a\n1\n2\n1\n1\n");
+        let output = temp_path("csv_to_json_zero.json");
+
+        let args = CsvToJsonArgs {
+            input: input.clone(),
+            output: output.clone(),
+            array_format: false,
+            delimiter: ',',
+            has_headers: true,
+            trim: false,
+            infer_types: false,
+            format: Format::Array,
+            columns: None,
+            num_rows: Some(0),
+            unflatten: false,
+            separator: ".".to_string(),
+            filter: Some("select(.a == \"1\")".to_string()),
+        };
+        csv_to_json(args).unwrap();
+
+        let parsed: Value = serde_json::from_str(&read_file(&output)).unwrap();
+        assert_eq!(parsed, serde_json::json!([]));
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn csv_to_json_rejects_array_format_with_filter() {
+        let input = temp_path("csv_to_json_reject.csv");
+        write_file(&input, "a\n1\n2\n");
+        let output = temp_path("csv_to_json_reject.json");
+
+        let args = CsvToJsonArgs {
+            input: input.clone(),
+            output: output.clone(),
+            array_format: true,
+            delimiter: ',',
+            has_headers: true,
+            trim: false,
+            infer_types: false,
+            format: Format::Array,
+            columns: None,
+            num_rows: None,
+            unflatten: false,
+            separator: ".".to_string(),
+            filter: Some("select(.a == \"1\")".to_string()),
+        };
+        assert!(csv_to_json(args).is_err());
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn json_to_csv_streaming_num_rows_caps_emitted_rows_not_lines_read() {
+        let input = temp_path("streaming_rows.ndjson");
+        write_file(&input, "{\"a\":\"1\"}\n{\"a\":\"2\"}\n{\"a\":\"1\"}\n{\"a\":\"1\"}\n");
+        let output = temp_path("streaming_rows.csv");
+
+        let jq_filter = JqFilter::compile("select(.a == \"1\")").unwrap();
+        let file = File::create(&output).unwrap();
+        let mut csv_writer = csv::WriterBuilder::new().from_writer(file);
+        let options = CsvWriteOptions {
+            columns: None,
+            num_rows: Some(2),
+            flatten: false,
+            separator: ".".to_string(),
+            headers_from_first: false,
+            jq_filter: Some(&jq_filter),
+        };
+        json_to_csv_streaming(&input, &mut csv_writer, options).unwrap();
+        csv_writer.flush().unwrap();
+
+        assert_eq!(read_file(&output), "a\n1\n1\n");
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn json_to_csv_streaming_num_rows_zero_writes_no_data_rows() {
+        let input = temp_path("streaming_zero.ndjson");
+        write_file(&input, "{\"a\":\"1\"}\n{\"a\":\"2\"}\n{\"a\":\"1\"}\n");
+        let output = temp_path("streaming_zero.csv");
+
+        let jq_filter = JqFilter::compile("select(.a == \"1\")").unwrap();
+        let file = File::create(&output).unwrap();
+        let mut csv_writer = csv::WriterBuilder::new().from_writer(file);
+        let options = CsvWriteOptions {
+            columns: None,
+            num_rows: Some(0),
+            flatten: false,
+            separator: ".".to_string(),
+            headers_from_first: false,
+            jq_filter: Some(&jq_filter),
+        };
+        json_to_csv_streaming(&input, &mut csv_writer, options).unwrap();
+        csv_writer.flush().unwrap();
+
+        assert_eq!(read_file(&output), "a\n");
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn json_to_csv_eager_filter_and_flatten_compose() {
+        let input = temp_path("eager_nested.json");
+        write_file(&input, r#"[{"a":{"b":1}},{"a":{"b":2}},{"a":{"b":3}}]"#);
+        let output = temp_path("eager_nested.csv");
+
+        let jq_filter = JqFilter::compile("select(.a.b > 1)").unwrap();
+        let file = File::create(&output).unwrap();
+        let mut csv_writer = csv::WriterBuilder::new().from_writer(file);
+        let options = CsvWriteOptions {
+            columns: None,
+            num_rows: None,
+            flatten: true,
+            separator: ".".to_string(),
+            headers_from_first: false,
+            jq_filter: Some(&jq_filter),
+        };
+        json_to_csv_eager(&input, &mut csv_writer, options).unwrap();
+        csv_writer.flush().unwrap();
+
+        assert_eq!(read_file(&output), "a.b\n2\n3\n");
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
 }
\ No newline at end of file